@@ -14,10 +14,12 @@
 
 // [x] PERF0001: handle select without grouping or windows easier.
 
+use std::collections::{HashMap, HashSet};
+
 use crate::{errors::Result, op::prelude::*, Event, Operator};
 use tremor_script::{
     self,
-    ast::{InvokeAggrFn, Select, SelectStmt},
+    ast::{ImutExpr, ImutExprInt, InvokeAggrFn, Path, Segment, Select, SelectStmt},
     errors::query_guard_not_bool,
     interpreter::{Env, LocalStack},
     prelude::*,
@@ -32,6 +34,10 @@ pub struct SimpleSelect {
     pub id: String,
     pub(crate) select: srs::Select,
     recursion_limit: u32,
+    /// the set of event paths read by this statement's guards, computed
+    /// once in [`SimpleSelect::with_stmt`] and used to avoid materializing
+    /// the full event before guard evaluation
+    liveness: Liveness,
 }
 
 const NO_AGGRS: [InvokeAggrFn<'static>; 0] = [];
@@ -39,10 +45,12 @@ const NO_AGGRS: [InvokeAggrFn<'static>; 0] = [];
 impl SimpleSelect {
     pub fn with_stmt(id: String, stmt: &srs::Stmt) -> Result<Self> {
         let select = srs::Select::try_new_from_stmt(stmt)?;
+        let liveness = select.rent(|SelectStmt { stmt, .. }| compute_liveness(stmt));
         Ok(Self {
             id,
             select,
             recursion_limit: tremor_script::recursion_limit(),
+            liveness,
         })
     }
     fn opts() -> ExecOpts {
@@ -53,6 +61,366 @@ impl SimpleSelect {
     }
 }
 
+/// a single segment of a statically known event path, e.g. `event.a.b` is
+/// `[Field("a"), Field("b")]`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum PathSegment {
+    Field(String),
+    Idx(usize),
+}
+
+/// the set of event paths read by a statement, as computed by
+/// [`compute_liveness`]
+#[derive(Debug, Clone, PartialEq)]
+enum Liveness {
+    /// the whole event may be read; no projection is safe
+    All,
+    /// only these paths (deduped so that a live `a` subsumes `a.b`) are read
+    Fields(HashSet<Vec<PathSegment>>),
+}
+
+impl Liveness {
+    fn empty() -> Self {
+        Liveness::Fields(HashSet::new())
+    }
+
+    fn mark_all(&mut self) {
+        *self = Liveness::All;
+    }
+
+    /// records that `path` is read, merging it with any existing paths it
+    /// subsumes or is subsumed by
+    fn insert(&mut self, path: Vec<PathSegment>) {
+        if let Liveness::Fields(fields) = self {
+            if fields.iter().any(|p| path.starts_with(p)) {
+                return;
+            }
+            fields.retain(|p| !p.starts_with(&path));
+            fields.insert(path);
+        }
+    }
+}
+
+/// a reverse (uses-before-defs) walk over the `where`/`having` expressions
+/// of a `select` statement, collecting the set of event paths they read
+///
+/// the target is deliberately not walked: `SimpleSelect` only ever handles
+/// `select event ...`, a bare `event` target, and the output event is
+/// always the raw input event ([`Operator::on_event`] returns `event.into()`
+/// unchanged) rather than anything reconstructed from the target
+/// expression, so the target contributes nothing useful to the projection
+/// and would otherwise always force [`Liveness::All`]
+///
+/// this mirrors classic liveness-over-AST dataflow: visiting a `Path`
+/// expression inserts its segment prefix into the live set, visiting any
+/// other sub-expression unions its children's sets. a bare `event`
+/// identifier, or a path with a dynamic segment such as `event[x]`, can read
+/// any part of the event and so forces [`Liveness::All`], since pruning
+/// would be unsound
+fn compute_liveness(stmt: &Select) -> Liveness {
+    let mut live = Liveness::empty();
+    if let Some(guard) = &stmt.maybe_where {
+        walk_imut_expr(guard, &mut live);
+    }
+    if let Some(guard) = &stmt.maybe_having {
+        walk_imut_expr(guard, &mut live);
+    }
+    live
+}
+
+fn walk_imut_expr(expr: &ImutExpr, live: &mut Liveness) {
+    if let Liveness::All = live {
+        return;
+    }
+    match expr.as_ref() {
+        ImutExprInt::Path(path) => walk_path(path, live),
+        ImutExprInt::Present { path, .. } => walk_path(path, live),
+        ImutExprInt::Binary(b) => {
+            walk_imut_expr(&b.lhs, live);
+            walk_imut_expr(&b.rhs, live);
+        }
+        ImutExprInt::Unary(u) => walk_imut_expr(&u.expr, live),
+        ImutExprInt::Record(r) => {
+            for field in &r.fields {
+                walk_imut_expr(&field.value, live);
+            }
+        }
+        ImutExprInt::List(l) => {
+            for e in &l.exprs {
+                walk_imut_expr(e, live);
+            }
+        }
+        ImutExprInt::Merge(m) => {
+            walk_imut_expr(&m.target, live);
+            walk_imut_expr(&m.expr, live);
+        }
+        ImutExprInt::Invoke(i) | ImutExprInt::Invoke1(i) | ImutExprInt::Invoke2(i) | ImutExprInt::Invoke3(i) => {
+            for a in &i.args {
+                walk_imut_expr(a, live);
+            }
+        }
+        ImutExprInt::Local { .. } | ImutExprInt::Literal(_) => {}
+        // anything we don't specifically understand (patch - whose
+        // insert/upsert/update/merge operation values can themselves read
+        // `event.*`; string - whose `"...#{event.x}..."` interpolation
+        // elements can too; along with match, comprehension, recur, bytes,
+        // ...) could read arbitrary parts of the event; pruning it away
+        // would be unsound, so fall back to the safe default
+        _ => live.mark_all(),
+    }
+}
+
+fn walk_path(path: &Path, live: &mut Liveness) {
+    if let Path::Event(p) = path {
+        match owned_segments(&p.segments) {
+            Some(segments) if is_projectable(&segments) => live.insert(segments),
+            // a bare `event`, a segment we can't resolve statically
+            // (`event[computed]`), or an indexed segment (`event.tags[0]`)
+            // that `project` has no way to pick an array element for,
+            // means the whole event may be read
+            _ => live.mark_all(),
+        }
+    }
+    // local/meta/state/const/reserved paths don't read the event
+}
+
+/// whether `segments` is a non-empty, purely field-keyed path that
+/// [`project`] can safely pick out of an event without reading the rest of
+/// it - i.e. not a bare `event` (empty) and not indexing into an array
+fn is_projectable(segments: &[PathSegment]) -> bool {
+    !segments.is_empty() && !segments.iter().any(|s| matches!(s, PathSegment::Idx(_)))
+}
+
+fn owned_segments(segments: &[Segment]) -> Option<Vec<PathSegment>> {
+    segments
+        .iter()
+        .map(|s| match s {
+            Segment::Id { key, .. } => Some(PathSegment::Field(key.to_string())),
+            Segment::Idx { idx, .. } => Some(PathSegment::Idx(*idx)),
+            // a computed (`Element`) or `Range` segment isn't known until
+            // runtime, so we can't soundly prune around it
+            _ => None,
+        })
+        .collect()
+}
+
+/// builds a `Value` containing only the paths in `fields`, without cloning
+/// anything else out of `value`
+fn project(value: &Value<'static>, fields: &HashSet<Vec<PathSegment>>) -> Value<'static> {
+    let mut by_head: HashMap<&PathSegment, Vec<&[PathSegment]>> = HashMap::new();
+    for path in fields {
+        if let Some((head, rest)) = path.split_first() {
+            by_head.entry(head).or_default().push(rest);
+        }
+    }
+    let mut out = Value::object_with_capacity(by_head.len());
+    for (head, rests) in by_head {
+        let PathSegment::Field(name) = head else {
+            continue;
+        };
+        let Some(v) = value.get(name.as_str()) else {
+            continue;
+        };
+        let projected = if rests.iter().any(|r| r.is_empty()) {
+            v.clone_static()
+        } else {
+            let rests: HashSet<Vec<PathSegment>> = rests.iter().map(|r| r.to_vec()).collect();
+            project(v, &rests)
+        };
+        out.try_insert(name.clone(), projected);
+    }
+    out
+}
+
+/// Graphviz DOT emitter primitives for operator nodes.
+///
+/// NOTE: this crate has no query/DAG type that owns the full set of
+/// compiled operators and walks them to emit a pipeline - that type, and
+/// the `to_dot(&self) -> String` the original request asked for on it, do
+/// not exist in this part of the codebase, so this module cannot deliver
+/// full operator-graph export on its own. What it does provide is the
+/// low-level writer the DAG type would need: one [`Writer::node`] call per
+/// operator, labeled via that operator's own contribution (see
+/// [`SimpleSelect::dot_label`]), and one [`Writer::edge`] call per
+/// connection between them. See the `test` module below for an example of
+/// composing these into a full document.
+pub mod dot {
+    use std::fmt::Write as _;
+
+    /// Selects between a directed (`digraph`) and undirected (`graph`)
+    /// export: the keyword and edge operator it uses follow from this.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Kind {
+        Digraph,
+        Graph,
+    }
+
+    impl Kind {
+        fn keyword(self) -> &'static str {
+            match self {
+                Kind::Digraph => "digraph",
+                Kind::Graph => "graph",
+            }
+        }
+
+        fn edge_op(self) -> &'static str {
+            match self {
+                Kind::Digraph => "->",
+                Kind::Graph => "--",
+            }
+        }
+    }
+
+    /// Incrementally builds a Graphviz DOT document.
+    pub struct Writer {
+        kind: Kind,
+        /// annotate edges with their source/destination port names; turn
+        /// this off for large graphs so they stay readable
+        pub show_ports: bool,
+        buf: String,
+    }
+
+    impl Writer {
+        pub fn new(kind: Kind, name: &str) -> Self {
+            let mut buf = String::new();
+            let _ = writeln!(buf, "{} {} {{", kind.keyword(), escape_id(name));
+            Self {
+                kind,
+                show_ports: true,
+                buf,
+            }
+        }
+
+        /// adds a node for an operator, labeled with its `id` and kind
+        pub fn node(&mut self, id: &str, label: &str) {
+            let _ = writeln!(self.buf, "  {} [label={}];", escape_id(id), escape_label(label));
+        }
+
+        /// adds a connection between two operators, optionally annotated
+        /// with the source and destination port names
+        pub fn edge(&mut self, from: &str, from_port: &str, to: &str, to_port: &str) {
+            if self.show_ports {
+                let _ = writeln!(
+                    self.buf,
+                    "  {} {} {} [label={}];",
+                    escape_id(from),
+                    self.kind.edge_op(),
+                    escape_id(to),
+                    escape_label(&format!("{}->{}", from_port, to_port))
+                );
+            } else {
+                let _ = writeln!(
+                    self.buf,
+                    "  {} {} {};",
+                    escape_id(from),
+                    self.kind.edge_op(),
+                    escape_id(to)
+                );
+            }
+        }
+
+        /// consumes the writer, returning the finished DOT document
+        pub fn finish(mut self) -> String {
+            self.buf.push_str("}\n");
+            self.buf
+        }
+    }
+
+    fn escape_id(s: &str) -> String {
+        format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+
+    fn escape_label(s: &str) -> String {
+        escape_id(s)
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+
+        #[test]
+        fn escape_id_wraps_in_quotes_and_escapes_quotes_and_backslashes() {
+            assert_eq!(escape_id(r#"weird "id" \ here"#), r#""weird \"id\" \\ here""#);
+        }
+
+        #[test]
+        fn escape_label_matches_escape_id() {
+            assert_eq!(escape_label("a \"b\""), escape_id("a \"b\""));
+        }
+
+        #[test]
+        fn node_emits_an_escaped_labeled_node() {
+            let mut w = Writer::new(Kind::Digraph, "pipeline");
+            w.node("select-1", r#"select-1 (simple-select)"#);
+            let dot = w.finish();
+            assert!(dot.starts_with("digraph \"pipeline\" {\n"));
+            assert!(dot.contains("\"select-1\" [label=\"select-1 (simple-select)\"];"));
+        }
+
+        #[test]
+        fn edge_annotates_ports_by_default() {
+            let mut w = Writer::new(Kind::Digraph, "pipeline");
+            w.edge("select-1", "out", "out", "in");
+            let dot = w.finish();
+            assert!(dot.contains("\"select-1\" -> \"out\" [label=\"out->in\"];"));
+        }
+
+        #[test]
+        fn edge_omits_ports_when_disabled() {
+            let mut w = Writer::new(Kind::Digraph, "pipeline");
+            w.show_ports = false;
+            w.edge("select-1", "out", "out", "in");
+            let dot = w.finish();
+            assert!(dot.contains("\"select-1\" -> \"out\";"));
+            assert!(!dot.contains("label"));
+        }
+
+        #[test]
+        fn graph_kind_uses_undirected_keyword_and_edge_operator() {
+            let mut w = Writer::new(Kind::Graph, "pipeline");
+            w.show_ports = false;
+            w.edge("a", "out", "b", "in");
+            let dot = w.finish();
+            assert!(dot.starts_with("graph \"pipeline\" {\n"));
+            assert!(dot.contains("\"a\" -- \"b\";"));
+        }
+
+        // A `SimpleSelect` can only be built from a parsed `srs::Select`,
+        // which this isolated module can't construct, so this stands in
+        // for a DAG type driving `Writer` across several operators: each
+        // node is labeled exactly as `SimpleSelect::write_dot` would label
+        // it (`dot_label`'s "<id> (simple-select)"), wired together with
+        // `edge`, to prove the pieces actually compose into one document.
+        #[test]
+        fn composes_into_a_multi_node_pipeline_document() {
+            let mut w = Writer::new(Kind::Digraph, "pipeline");
+            w.node("select-1", "select-1 (simple-select)");
+            w.node("select-2", "select-2 (simple-select)");
+            w.edge("select-1", "out", "select-2", "in");
+            let dot = w.finish();
+            assert!(dot.contains("\"select-1\" [label=\"select-1 (simple-select)\"];"));
+            assert!(dot.contains("\"select-2\" [label=\"select-2 (simple-select)\"];"));
+            assert!(dot.contains("\"select-1\" -> \"select-2\" [label=\"out->in\"];"));
+            assert!(dot.ends_with("}\n"));
+        }
+    }
+}
+
+impl SimpleSelect {
+    /// the node label this operator contributes to a Graphviz export of the
+    /// compiled pipeline: its `id` and operator kind
+    pub fn dot_label(&self) -> String {
+        format!("{} (simple-select)", self.id)
+    }
+
+    /// writes this operator's node into `writer`; the owning DAG type is
+    /// responsible for connecting it to its neighbours via their `in`/`out`
+    /// ports
+    pub fn write_dot(&self, writer: &mut dot::Writer) {
+        writer.node(&self.id, &self.dot_label());
+    }
+}
+
 impl Operator for SimpleSelect {
     fn on_event(
         &mut self,
@@ -87,8 +455,21 @@ impl Operator for SimpleSelect {
                     meta: node_meta,
                     recursion_limit: self.recursion_limit,
                 };
+                // when the live set is a statically-known set of paths
+                // (whatever its size), guard evaluation only ever needs those
+                // paths, so we project down to them instead of handing the
+                // guards the full (possibly much larger) event
+                let projected;
+                let (data, meta) = event.data.parts();
+                let data = match &self.liveness {
+                    Liveness::All => data,
+                    Liveness::Fields(fields) => {
+                        projected = project(data, fields);
+                        &projected
+                    }
+                };
+
                 if let Some(guard) = &stmt.maybe_where {
-                    let (data, meta) = event.data.parts();
                     let test = guard.run(opts, &env, data, state, meta, &local_stack)?;
                     if let Some(test) = test.as_bool() {
                         if !test {
@@ -100,8 +481,6 @@ impl Operator for SimpleSelect {
                 }
 
                 if let Some(guard) = &stmt.maybe_having {
-                    let (data, meta) = event.data.parts();
-
                     let test = guard.run(opts, &env, data, state, meta, &local_stack)?;
                     if let Some(test) = test.as_bool() {
                         if !test {
@@ -117,3 +496,88 @@ impl Operator for SimpleSelect {
         )
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn field(name: &str) -> PathSegment {
+        PathSegment::Field(name.to_string())
+    }
+
+    #[test]
+    fn is_projectable_rejects_bare_event() {
+        assert!(!is_projectable(&[]));
+    }
+
+    #[test]
+    fn is_projectable_rejects_indexed_segments() {
+        assert!(!is_projectable(&[field("tags"), PathSegment::Idx(0)]));
+    }
+
+    #[test]
+    fn is_projectable_accepts_field_only_paths() {
+        assert!(is_projectable(&[field("a"), field("b")]));
+    }
+
+    #[test]
+    fn liveness_insert_dedupes_subsumed_children() {
+        let mut live = Liveness::empty();
+        live.insert(vec![field("a"), field("b")]);
+        live.insert(vec![field("a")]);
+        assert_eq!(live, Liveness::Fields(vec![vec![field("a")]].into_iter().collect()));
+    }
+
+    #[test]
+    fn liveness_insert_ignores_children_of_a_live_parent() {
+        let mut live = Liveness::empty();
+        live.insert(vec![field("a")]);
+        live.insert(vec![field("a"), field("b")]);
+        assert_eq!(live, Liveness::Fields(vec![vec![field("a")]].into_iter().collect()));
+    }
+
+    #[test]
+    fn project_picks_only_live_top_level_fields() {
+        let value = literal!({
+            "a": 1,
+            "b": 2,
+            "c": 3
+        });
+        let fields: HashSet<Vec<PathSegment>> = vec![vec![field("a")]].into_iter().collect();
+        let projected = project(&value, &fields);
+        assert_eq!(projected.get("a"), value.get("a"));
+        assert_eq!(projected.get("b"), None);
+        assert_eq!(projected.get("c"), None);
+    }
+
+    #[test]
+    fn project_recurses_into_nested_live_fields() {
+        let value = literal!({
+            "a": {
+                "keep": 1,
+                "drop": 2
+            },
+            "b": 3
+        });
+        let fields: HashSet<Vec<PathSegment>> = vec![vec![field("a"), field("keep")]].into_iter().collect();
+        let projected = project(&value, &fields);
+        assert_eq!(projected.get("b"), None);
+        let a = projected.get("a").expect("a is live");
+        assert_eq!(a.get("keep"), Some(&Value::from(1)));
+        assert_eq!(a.get("drop"), None);
+    }
+
+    #[test]
+    fn project_takes_the_whole_field_when_a_shorter_prefix_is_also_live() {
+        let value = literal!({
+            "a": {
+                "keep": 1
+            }
+        });
+        let fields: HashSet<Vec<PathSegment>> = vec![vec![field("a")], vec![field("a"), field("keep")]]
+            .into_iter()
+            .collect();
+        let projected = project(&value, &fields);
+        assert_eq!(projected.get("a"), value.get("a"));
+    }
+}